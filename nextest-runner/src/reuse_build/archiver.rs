@@ -9,24 +9,147 @@ use crate::{
     reuse_build::PathMapper,
 };
 use atomicwrites::{AtomicFile, OverwriteBehavior};
-use camino::Utf8Path;
+use camino::{Utf8Path, Utf8PathBuf};
+use globset::{Glob, GlobMatcher};
 use std::{
+    fs::File,
     io::{self, BufWriter, Write},
     time::{Instant, SystemTime},
 };
+use zip::{write::FileOptions, ZipWriter};
 use zstd::Encoder;
 
+/// The subdirectory that "extra files" (see [`ExtraPathMapping`]) are bundled under within an
+/// archive.
+const EXTRA_FILES_PREFIX: &str = "extra";
+
+/// Collapses a Unix permission mode down to `0o755` (if any execute bit is set) or `0o644`,
+/// discarding setuid/setgid/sticky bits and group/other write access, so that the archive doesn't
+/// encode the umask of the machine it was built on.
+#[cfg(unix)]
+fn normalize_unix_mode(mode: u32) -> u32 {
+    if mode & 0o111 != 0 {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+/// Whether a path matching an [`ExtraPathRule`] should be included in or excluded from the
+/// archive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtraPathAction {
+    /// Include the path in the archive.
+    Include,
+    /// Exclude the path from the archive.
+    Exclude,
+}
+
+/// A single glob rule used to select which files under an [`ExtraPathMapping`]'s root get
+/// bundled into the archive.
+#[derive(Debug)]
+pub struct ExtraPathRule {
+    action: ExtraPathAction,
+    glob: GlobMatcher,
+}
+
+impl ExtraPathRule {
+    /// Creates a rule that includes paths (relative to the mapping's root) matching `pattern`.
+    pub fn include(pattern: &str) -> Result<Self, globset::Error> {
+        Self::new(ExtraPathAction::Include, pattern)
+    }
+
+    /// Creates a rule that excludes paths (relative to the mapping's root) matching `pattern`.
+    pub fn exclude(pattern: &str) -> Result<Self, globset::Error> {
+        Self::new(ExtraPathAction::Exclude, pattern)
+    }
+
+    fn new(action: ExtraPathAction, pattern: &str) -> Result<Self, globset::Error> {
+        Ok(Self {
+            action,
+            glob: Glob::new(pattern)?.compile_matcher(),
+        })
+    }
+}
+
+/// An ordered list of [`ExtraPathRule`]s, plus a default action for paths that don't match any of
+/// them.
+///
+/// Rules are evaluated in the order they were added against the path relative to the mapping's
+/// root; the *last* matching rule wins. This is the same "last match wins" evaluation order that
+/// proxmox's `pxar` extractor uses for its match groups, and it lets a later broad exclude be
+/// carved back open by a narrower include (or vice versa).
+#[derive(Debug)]
+pub struct ExtraPathRuleSet {
+    rules: Vec<ExtraPathRule>,
+    default_action: ExtraPathAction,
+}
+
+impl ExtraPathRuleSet {
+    /// Creates a new rule set that falls back to `default_action` for paths matched by no rule.
+    pub fn new(default_action: ExtraPathAction) -> Self {
+        Self {
+            rules: Vec::new(),
+            default_action,
+        }
+    }
+
+    /// Appends a rule to the end of the set.
+    pub fn add_rule(mut self, rule: ExtraPathRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    fn action_for(&self, rel_path: &Utf8Path) -> ExtraPathAction {
+        self.matching_rule(rel_path)
+            .map_or(self.default_action, |rule| rule.action)
+    }
+
+    /// Returns the last-added rule that explicitly matches `rel_path`, or `None` if no rule
+    /// matched and the result fell back to `default_action`.
+    ///
+    /// This is distinct from `action_for`: a directory can be excluded by `default_action` while
+    /// still containing files that an explicit include rule would match (e.g. `**/*.txt` doesn't
+    /// match the directory itself), so callers that need to know whether a directory can be
+    /// safely pruned from a walk should check for an explicit match rather than the resolved
+    /// action.
+    fn matching_rule(&self, rel_path: &Utf8Path) -> Option<&ExtraPathRule> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.glob.is_match(rel_path.as_str()))
+    }
+}
+
+/// A root (file or directory) to bundle into the archive as "extra files", along with the rules
+/// that select which of its contents are included.
+///
+/// Matching files are stored under a stable `extra/<root name>/...` prefix so that unarchiving
+/// always produces the same layout regardless of where the root lived on the machine that built
+/// the archive.
+#[derive(Debug)]
+pub struct ExtraPathMapping {
+    /// The root path to walk on disk.
+    pub root: Utf8PathBuf,
+    /// The rules used to select files under `root`.
+    pub rules: ExtraPathRuleSet,
+}
+
 /// Archive format.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[non_exhaustive]
 pub enum ArchiveFormat {
     /// A Zstandard-compressed tarball.
     TarZst,
+
+    /// A zip archive, for use on machines that lack zstd tooling (e.g. some Windows CI runners).
+    Zip,
 }
 
 impl ArchiveFormat {
     /// The list of supported formats as a list of (file extension, format) pairs.
-    pub const SUPPORTED_FORMATS: &'static [(&'static str, Self)] = &[(".tar.zst", Self::TarZst)];
+    pub const SUPPORTED_FORMATS: &'static [(&'static str, Self)] =
+        &[(".tar.zst", Self::TarZst), (".zip", Self::Zip)];
 
     /// Automatically detects an archive format from a given file name, and returns an error if the
     /// detection failed.
@@ -46,7 +169,30 @@ impl ArchiveFormat {
 
 /// Archives test binaries along with metadata to the given file.
 ///
-/// The output file is a Zstandard-compressed tarball (`.tar.zst`).
+/// The output file is either a Zstandard-compressed tarball (`.tar.zst`) or a zip archive,
+/// depending on `format`.
+///
+/// If `reproducible` is `Some(epoch)`, every entry is stamped with `epoch` (typically a
+/// `SOURCE_DATE_EPOCH` value, or 0) instead of the current time, permissions are normalized, and
+/// entries are written in a deterministic order, so that archiving identical inputs twice
+/// produces byte-for-byte identical output. If `reproducible` is `None`, archives use the current
+/// time and on-disk ordering, as before. Zip entries are the exception to the `epoch` stamp: in
+/// reproducible mode they all carry the zip format's default timestamp (1980-01-01 00:00:00), and
+/// `epoch` itself is not recorded in them.
+///
+/// If `preserve_symlinks` is true, symlinks found under `linked_paths` or `extra_paths` are
+/// stored as symlink entries pointing at their target, and (on Unix, in `.tar.zst` archives only)
+/// each regular file's extended attributes are carried along as well, instead of dereferencing
+/// symlinks and dropping xattrs. Zip archives do not carry xattrs.
+///
+/// Note: this crate only covers the archiving side. Restoring an archive built with
+/// `preserve_symlinks: true` faithfully (re-creating symlinks instead of copies, and restoring
+/// xattrs from the PAX extended headers) is the responsibility of whatever extractor unpacks the
+/// reuse-build archive; a plain `tar -x`/`unzip` will dereference symlinks into regular files and
+/// drop the xattr headers.
+///
+/// This is a thin wrapper around [`archive_to_writer`] that supplies an atomic-file writer; see
+/// that function if you need to stream the archive somewhere other than a seekable file.
 pub fn archive_to_file<'a, F>(
     binary_list: &'a BinaryList,
     cargo_metadata: &'a str,
@@ -54,37 +200,32 @@ pub fn archive_to_file<'a, F>(
     format: ArchiveFormat,
     zstd_level: i32,
     output_file: &'a Utf8Path,
+    extra_paths: &'a [ExtraPathMapping],
+    reproducible: Option<u64>,
+    preserve_symlinks: bool,
     mut callback: F,
 ) -> Result<(), ArchiveCreateError>
 where
     F: FnMut(ArchiveEvent<'a>) -> io::Result<()>,
 {
     let file = AtomicFile::new(output_file, OverwriteBehavior::AllowOverwrite);
-    let test_binary_count = binary_list.rust_binaries.len();
-    let non_test_binary_count = binary_list.rust_build_meta.non_test_binaries.len();
-    let linked_path_count = binary_list.rust_build_meta.linked_paths.len();
     let start_time = Instant::now();
 
     let file_count = file
         .write(|file| {
-            callback(ArchiveEvent::ArchiveStarted {
-                test_binary_count,
-                non_test_binary_count,
-                linked_path_count,
-                output_file,
-            })
-            .map_err(ArchiveCreateError::ReporterIo)?;
-            // Write out the archive.
-            let archiver = Archiver::new(
+            archive_to_writer(
                 binary_list,
                 cargo_metadata,
                 path_mapper,
                 format,
                 zstd_level,
+                extra_paths,
+                reproducible,
+                preserve_symlinks,
+                output_file,
                 file,
-            )?;
-            let (_, file_count) = archiver.archive()?;
-            Ok(file_count)
+                &mut callback,
+            )
         })
         .map_err(|err| match err {
             atomicwrites::Error::Internal(err) => ArchiveCreateError::OutputArchiveIo(err),
@@ -103,12 +244,164 @@ where
     Ok(())
 }
 
+/// Archives test binaries along with metadata, writing the result directly to `writer`.
+///
+/// Unlike [`archive_to_file`], this doesn't go through a temporary file and doesn't require
+/// `writer` to be seekable: the `.tar.zst` backend is fully streaming and never seeks, so this can
+/// be used to pipe an archive straight to `ssh`, an object-store upload, or another process's
+/// stdin. (The zip backend still needs to buffer and rewrite its central directory on `finish`,
+/// so it gains nothing from a non-seekable sink, but works the same way here as it does through
+/// `archive_to_file`.)
+///
+/// `output_file` is used only for [`ArchiveEvent::ArchiveStarted`] reporting; when streaming to a
+/// pipe where there's no real path, pass a descriptive placeholder (e.g. `-` or `<stdout>`).
+///
+/// If `extra_paths` selects at least one file, [`ArchiveEvent::ExtraFilesArchived`] is reported
+/// once archiving finishes, so callers can report how many extra files were bundled.
+///
+/// Returns the number of files written to the archive.
+pub fn archive_to_writer<'a, F, W>(
+    binary_list: &'a BinaryList,
+    cargo_metadata: &'a str,
+    path_mapper: &'a PathMapper,
+    format: ArchiveFormat,
+    zstd_level: i32,
+    extra_paths: &'a [ExtraPathMapping],
+    reproducible: Option<u64>,
+    preserve_symlinks: bool,
+    output_file: &'a Utf8Path,
+    writer: W,
+    mut callback: F,
+) -> Result<usize, ArchiveCreateError>
+where
+    F: FnMut(ArchiveEvent<'a>) -> io::Result<()>,
+    W: Write,
+{
+    let test_binary_count = binary_list.rust_binaries.len();
+    let non_test_binary_count = binary_list.rust_build_meta.non_test_binaries.len();
+    let linked_path_count = binary_list.rust_build_meta.linked_paths.len();
+
+    callback(ArchiveEvent::ArchiveStarted {
+        test_binary_count,
+        non_test_binary_count,
+        linked_path_count,
+        output_file,
+    })
+    .map_err(ArchiveCreateError::ReporterIo)?;
+
+    let archiver = Archiver::new(
+        binary_list,
+        cargo_metadata,
+        path_mapper,
+        format,
+        zstd_level,
+        extra_paths,
+        reproducible,
+        preserve_symlinks,
+        writer,
+    )?;
+    let (_, file_count, extra_file_count) = archiver.archive()?;
+
+    if extra_file_count > 0 {
+        callback(ArchiveEvent::ExtraFilesArchived { extra_file_count })
+            .map_err(ArchiveCreateError::ReporterIo)?;
+    }
+
+    Ok(file_count)
+}
+
+/// Walks `root`, returning the paths (relative to `root`) that `rules` selects for inclusion.
+///
+/// Pulled out of `Archiver::append_extra_path` so the selection logic — in particular the
+/// directory-pruning decision — can be exercised directly against a real directory tree without
+/// needing a full `Archiver`.
+fn select_extra_dir_paths(
+    root: &Utf8Path,
+    rules: &ExtraPathRuleSet,
+    reproducible: Option<u64>,
+) -> Result<Vec<Utf8PathBuf>, ArchiveCreateError> {
+    let mut selected = Vec::new();
+
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(src) = stack.pop() {
+        let mut children = Vec::new();
+        for entry in src
+            .read_dir_utf8()
+            .map_err(|error| ArchiveCreateError::InputFileRead {
+                path: src.clone(),
+                is_dir: Some(true),
+                error,
+            })?
+        {
+            let entry = entry.map_err(|error| ArchiveCreateError::DirEntryRead {
+                path: src.clone(),
+                error,
+            })?;
+            let file_type = entry
+                .file_type()
+                .map_err(|error| ArchiveCreateError::InputFileRead {
+                    path: entry.path().to_owned(),
+                    is_dir: None,
+                    error,
+                })?;
+
+            children.push((entry.path().to_path_buf(), file_type.is_dir()));
+        }
+        // Directory iteration order is filesystem-dependent; sort it so reproducible archives
+        // don't depend on it. Push in reverse so the stack (a LIFO) still pops entries in
+        // ascending order.
+        if reproducible.is_some() {
+            children.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+
+        for (entry_path, is_dir) in children.into_iter().rev() {
+            let rel_path = entry_path
+                .strip_prefix(root)
+                .expect("extra file paths must be within their root");
+
+            if is_dir {
+                // Prune a directory only if a rule explicitly excludes it. Falling back to
+                // `default_action` isn't enough to prune: a glob like `**/*.txt` never matches
+                // the directory path itself, so relying on the resolved action would drop files
+                // further down the tree that an include rule was meant to match.
+                let prune = matches!(
+                    rules.matching_rule(rel_path),
+                    Some(rule) if rule.action == ExtraPathAction::Exclude
+                );
+                if !prune {
+                    stack.push(entry_path);
+                }
+                continue;
+            }
+
+            if rules.action_for(rel_path) == ExtraPathAction::Include {
+                selected.push(rel_path.to_path_buf());
+            }
+        }
+    }
+
+    Ok(selected)
+}
+
+/// The backend that entries are written through, one per supported [`ArchiveFormat`].
+enum ArchiveBuilder<W: Write> {
+    TarZst(tar::Builder<Encoder<'static, BufWriter<W>>>),
+    Zip(ZipWriter<BufWriter<W>>),
+}
+
 struct Archiver<'a, W: Write> {
     binary_list: &'a BinaryList,
     cargo_metadata: &'a str,
     path_mapper: &'a PathMapper,
-    builder: tar::Builder<Encoder<'static, BufWriter<W>>>,
+    extra_paths: &'a [ExtraPathMapping],
+    builder: ArchiveBuilder<W>,
     unix_timestamp: u64,
+    // `Some` makes archiving deterministic: a fixed mtime, normalized permissions, and a stable
+    // entry order, so that archiving the same inputs twice produces the same bytes.
+    reproducible: Option<u64>,
+    // If true, symlinks are stored as symlinks (rather than dereferenced) and regular files carry
+    // their extended attributes along with them.
+    preserve_symlinks: bool,
     file_count: usize,
 }
 
@@ -119,6 +412,9 @@ impl<'a, W: Write> Archiver<'a, W> {
         path_mapper: &'a PathMapper,
         format: ArchiveFormat,
         compression_level: i32,
+        extra_paths: &'a [ExtraPathMapping],
+        reproducible: Option<u64>,
+        preserve_symlinks: bool,
         writer: W,
     ) -> Result<Self, ArchiveCreateError> {
         let buf_writer = BufWriter::new(writer);
@@ -132,21 +428,28 @@ impl<'a, W: Write> Archiver<'a, W> {
                 encoder
                     .multithread(num_cpus::get() as u32)
                     .map_err(ArchiveCreateError::OutputArchiveIo)?;
-                tar::Builder::new(encoder)
+                ArchiveBuilder::TarZst(tar::Builder::new(encoder))
             }
+            ArchiveFormat::Zip => ArchiveBuilder::Zip(ZipWriter::new(buf_writer)),
         };
 
-        let unix_timestamp = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .expect("current time should be after 1970-01-01")
-            .as_secs();
+        let unix_timestamp = match reproducible {
+            Some(epoch) => epoch,
+            None => SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .expect("current time should be after 1970-01-01")
+                .as_secs(),
+        };
 
         Ok(Self {
             binary_list,
             cargo_metadata,
             path_mapper,
+            extra_paths,
             builder,
             unix_timestamp,
+            reproducible,
+            preserve_symlinks,
             file_count: 0,
         })
     }
@@ -165,7 +468,11 @@ impl<'a, W: Write> Archiver<'a, W> {
         // Write all discovered binaries into the archive.
         let target_dir = &self.binary_list.rust_build_meta.target_directory;
 
-        for binary in &self.binary_list.rust_binaries {
+        let mut rust_binaries: Vec<_> = self.binary_list.rust_binaries.iter().collect();
+        if self.reproducible.is_some() {
+            rust_binaries.sort_by(|a, b| a.path.cmp(&b.path));
+        }
+        for binary in rust_binaries {
             let rel_path = binary
                 .path
                 .strip_prefix(target_dir.parent().expect("target dir cannot be the root"))
@@ -175,13 +482,18 @@ impl<'a, W: Write> Archiver<'a, W> {
             self.append_path(&binary.path, &rel_path)?;
             self.file_count += 1;
         }
-        for non_test_binary in self
+
+        let mut non_test_binaries: Vec<_> = self
             .binary_list
             .rust_build_meta
             .non_test_binaries
             .iter()
             .flat_map(|(_, binaries)| binaries)
-        {
+            .collect();
+        if self.reproducible.is_some() {
+            non_test_binaries.sort_by(|a, b| a.path.cmp(&b.path));
+        }
+        for non_test_binary in non_test_binaries {
             let src_path = self
                 .binary_list
                 .rust_build_meta
@@ -208,25 +520,34 @@ impl<'a, W: Write> Archiver<'a, W> {
 
             let rel_path = Utf8Path::new("target").join(linked_path);
             let rel_path = convert_rel_path_to_forward_slash(&rel_path);
-            self.append_dir_all(&rel_path, &src_path, true)?;
+            self.append_dir_all(&rel_path, &src_path, !self.preserve_symlinks)?;
         }
 
-        // TODO: add extra files.
+        // Add any user-specified extra files, filtered by their match rules.
+        let mut extra_file_count = 0;
+        for extra_path in self.extra_paths {
+            extra_file_count += self.append_extra_path(extra_path)?;
+        }
 
         // Finish writing the archive.
-        let encoder = self
-            .builder
-            .into_inner()
-            .map_err(ArchiveCreateError::OutputArchiveIo)?;
-        // Finish writing the zstd stream.
-        let buf_writer = encoder
-            .finish()
-            .map_err(ArchiveCreateError::OutputArchiveIo)?;
+        let buf_writer = match self.builder {
+            ArchiveBuilder::TarZst(builder) => {
+                let encoder = builder
+                    .into_inner()
+                    .map_err(ArchiveCreateError::OutputArchiveIo)?;
+                // Finish writing the zstd stream.
+                encoder.finish().map_err(ArchiveCreateError::OutputArchiveIo)?
+            }
+            ArchiveBuilder::Zip(writer) => writer
+                .finish()
+                .map_err(io::Error::from)
+                .map_err(ArchiveCreateError::OutputArchiveIo)?,
+        };
         let writer = buf_writer
             .into_inner()
             .map_err(|err| ArchiveCreateError::OutputArchiveIo(err.into_error()))?;
 
-        Ok((writer, self.file_count))
+        Ok((writer, self.file_count, extra_file_count))
     }
 
     // ---
@@ -234,15 +555,32 @@ impl<'a, W: Write> Archiver<'a, W> {
     // ---
 
     fn append_from_memory(&mut self, name: &str, contents: &str) -> Result<(), ArchiveCreateError> {
-        let mut header = tar::Header::new_gnu();
-        header.set_size(contents.len() as u64);
-        header.set_mtime(self.unix_timestamp);
-        header.set_mode(0o664);
-        header.set_cksum();
-
-        self.builder
-            .append_data(&mut header, name, io::Cursor::new(contents))
-            .map_err(ArchiveCreateError::OutputArchiveIo)?;
+        match &mut self.builder {
+            ArchiveBuilder::TarZst(builder) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(contents.len() as u64);
+                header.set_mtime(self.unix_timestamp);
+                header.set_mode(0o664);
+                header.set_cksum();
+
+                builder
+                    .append_data(&mut header, name, io::Cursor::new(contents))
+                    .map_err(ArchiveCreateError::OutputArchiveIo)?;
+            }
+            ArchiveBuilder::Zip(writer) => {
+                let mut options = FileOptions::default().unix_permissions(0o664);
+                if self.reproducible.is_some() {
+                    options = options.last_modified_time(zip::DateTime::default());
+                }
+                writer
+                    .start_file(name, options)
+                    .map_err(io::Error::from)
+                    .map_err(ArchiveCreateError::OutputArchiveIo)?;
+                writer
+                    .write_all(contents.as_bytes())
+                    .map_err(ArchiveCreateError::OutputArchiveIo)?;
+            }
+        }
         self.file_count += 1;
         Ok(())
     }
@@ -260,6 +598,7 @@ impl<'a, W: Write> Archiver<'a, W> {
             let dest = rel_path.join(src.strip_prefix(&src_path).unwrap());
             // In case of a symlink pointing to a directory, is_dir is false, but src.is_dir() will return true
             if is_dir || (is_symlink && follow && src.is_dir()) {
+                let mut children = Vec::new();
                 for entry in
                     src.read_dir_utf8()
                         .map_err(|error| ArchiveCreateError::InputFileRead {
@@ -281,12 +620,19 @@ impl<'a, W: Write> Archiver<'a, W> {
                                 is_dir: None,
                                 error,
                             })?;
-                    stack.push((
+                    children.push((
                         entry.path().to_path_buf(),
                         file_type.is_dir(),
                         file_type.is_symlink(),
                     ));
                 }
+                // Directory iteration order is filesystem-dependent; sort it so reproducible
+                // archives don't depend on it. Push in reverse so the stack (a LIFO) still pops
+                // entries in ascending order.
+                if self.reproducible.is_some() {
+                    children.sort_by(|a, b| a.0.cmp(&b.0));
+                }
+                stack.extend(children.into_iter().rev());
                 // No need to append the directory entry to the tarball since we don't care about
                 // its metadata.
             } else {
@@ -297,17 +643,250 @@ impl<'a, W: Write> Archiver<'a, W> {
         Ok(())
     }
 
+    // Walks `extra_path.root`, appending every file selected by its rule set under a stable
+    // `extra/<root name>/...` prefix. Directories that the rule set excludes outright are pruned
+    // from the walk rather than descended into, so a large excluded directory doesn't cost a full
+    // traversal just to discard everything under it.
+    fn append_extra_path(&mut self, extra_path: &ExtraPathMapping) -> Result<usize, ArchiveCreateError> {
+        let root_name = extra_path
+            .root
+            .file_name()
+            .unwrap_or(EXTRA_FILES_PREFIX);
+        let dest_root = Utf8Path::new(EXTRA_FILES_PREFIX).join(root_name);
+        let mut extra_file_count = 0;
+
+        if !extra_path.root.is_dir() {
+            let rel_path = Utf8Path::new(root_name);
+            if extra_path.rules.action_for(rel_path) == ExtraPathAction::Include {
+                let dest = convert_rel_path_to_forward_slash(&dest_root);
+                self.append_path(&extra_path.root, &dest)?;
+                extra_file_count += 1;
+            }
+            return Ok(extra_file_count);
+        }
+
+        for rel_path in
+            select_extra_dir_paths(&extra_path.root, &extra_path.rules, self.reproducible)?
+        {
+            let dest = convert_rel_path_to_forward_slash(&dest_root.join(&rel_path));
+            self.append_path(&extra_path.root.join(&rel_path), &dest)?;
+            extra_file_count += 1;
+        }
+
+        Ok(extra_file_count)
+    }
+
     fn append_path(&mut self, src: &Utf8Path, dest: &Utf8Path) -> Result<(), ArchiveCreateError> {
-        self.builder
-            .append_path_with_name(src, dest)
-            .map_err(|error| ArchiveCreateError::InputFileRead {
-                path: src.to_owned(),
-                is_dir: Some(false),
-                error,
+        if self.preserve_symlinks {
+            let metadata = std::fs::symlink_metadata(src.as_std_path()).map_err(|error| {
+                ArchiveCreateError::InputFileRead {
+                    path: src.to_owned(),
+                    is_dir: Some(false),
+                    error,
+                }
             })?;
+            if metadata.file_type().is_symlink() {
+                let target = std::fs::read_link(src.as_std_path()).map_err(|error| {
+                    ArchiveCreateError::InputFileRead {
+                        path: src.to_owned(),
+                        is_dir: Some(false),
+                        error,
+                    }
+                })?;
+                return self.append_symlink(dest, &target);
+            }
+        }
+
+        let reproducible = self.reproducible;
+        #[cfg(unix)]
+        let preserve_symlinks = self.preserve_symlinks;
+        #[cfg(unix)]
+        let unix_timestamp = self.unix_timestamp;
+        match &mut self.builder {
+            ArchiveBuilder::TarZst(builder) => {
+                #[cfg(unix)]
+                if preserve_symlinks {
+                    append_xattrs_pax_header(builder, src, unix_timestamp)?;
+                }
+
+                if let Some(mtime) = reproducible {
+                    // Build the header ourselves so we can normalize it with tar-rs's
+                    // deterministic mode, rather than the real (and thus non-reproducible)
+                    // mtime/uid/gid that `append_path_with_name` would otherwise preserve.
+                    let mut file =
+                        File::open(src).map_err(|error| ArchiveCreateError::InputFileRead {
+                            path: src.to_owned(),
+                            is_dir: Some(false),
+                            error,
+                        })?;
+                    let metadata =
+                        file.metadata()
+                            .map_err(|error| ArchiveCreateError::InputFileRead {
+                                path: src.to_owned(),
+                                is_dir: Some(false),
+                                error,
+                            })?;
+
+                    let mut header = tar::Header::new_gnu();
+                    header.set_metadata_in_mode(&metadata, tar::HeaderMode::Deterministic);
+                    header.set_mtime(mtime);
+                    header.set_cksum();
+
+                    builder
+                        .append_data(&mut header, dest, &mut file)
+                        .map_err(ArchiveCreateError::OutputArchiveIo)?;
+                } else {
+                    builder.append_path_with_name(src, dest).map_err(|error| {
+                        ArchiveCreateError::InputFileRead {
+                            path: src.to_owned(),
+                            is_dir: Some(false),
+                            error,
+                        }
+                    })?;
+                }
+            }
+            ArchiveBuilder::Zip(writer) => {
+                let mut file =
+                    File::open(src).map_err(|error| ArchiveCreateError::InputFileRead {
+                        path: src.to_owned(),
+                        is_dir: Some(false),
+                        error,
+                    })?;
+
+                let mut options = FileOptions::default();
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    let mode = file
+                        .metadata()
+                        .map_err(|error| ArchiveCreateError::InputFileRead {
+                            path: src.to_owned(),
+                            is_dir: Some(false),
+                            error,
+                        })?
+                        .permissions()
+                        .mode();
+                    let mode = if reproducible.is_some() {
+                        normalize_unix_mode(mode)
+                    } else {
+                        mode
+                    };
+                    options = options.unix_permissions(mode);
+                }
+                if reproducible.is_some() {
+                    options = options.last_modified_time(zip::DateTime::default());
+                }
+
+                writer
+                    .start_file(dest.as_str(), options)
+                    .map_err(io::Error::from)
+                    .map_err(ArchiveCreateError::OutputArchiveIo)?;
+                io::copy(&mut file, writer).map_err(ArchiveCreateError::OutputArchiveIo)?;
+            }
+        }
         self.file_count += 1;
         Ok(())
     }
+
+    // Stores `target` as the link target of a real symlink entry at `dest`, instead of
+    // dereferencing it. Only called when `preserve_symlinks` is set.
+    fn append_symlink(
+        &mut self,
+        dest: &Utf8Path,
+        target: &std::path::Path,
+    ) -> Result<(), ArchiveCreateError> {
+        match &mut self.builder {
+            ArchiveBuilder::TarZst(builder) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_entry_type(tar::EntryType::Symlink);
+                header.set_size(0);
+                header.set_mtime(self.unix_timestamp);
+                header.set_mode(0o777);
+                header.set_cksum();
+                // tar-rs emits a GNU long-link (and, for ustar-incompatible targets, a PAX
+                // `linkpath` extended header) automatically when `target` doesn't fit in the
+                // 100-byte ustar `linkname` field.
+                builder
+                    .append_link(&mut header, dest, target)
+                    .map_err(ArchiveCreateError::OutputArchiveIo)?;
+            }
+            ArchiveBuilder::Zip(writer) => {
+                let target = target.to_str().ok_or_else(|| {
+                    ArchiveCreateError::OutputArchiveIo(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("symlink target {} is not valid UTF-8", target.display()),
+                    ))
+                })?;
+                // zip has no first-class symlink entry type; the de facto convention (used by
+                // Info-ZIP and supported by most extractors) is to store the link target as the
+                // entry's contents and flag it via the `S_IFLNK` bit in the Unix mode recorded in
+                // the external file attributes.
+                let mut options = FileOptions::default().unix_permissions(0o120_777);
+                if self.reproducible.is_some() {
+                    options = options.last_modified_time(zip::DateTime::default());
+                }
+                writer
+                    .start_file(dest.as_str(), options)
+                    .map_err(io::Error::from)
+                    .map_err(ArchiveCreateError::OutputArchiveIo)?;
+                writer
+                    .write_all(target.as_bytes())
+                    .map_err(ArchiveCreateError::OutputArchiveIo)?;
+            }
+        }
+        self.file_count += 1;
+        Ok(())
+    }
+}
+
+/// Emits a PAX extended header recording every extended attribute on `src`, so that
+/// `SCHILY.xattr.*`-aware extractors (e.g. GNU tar with `--xattrs`) can restore them.
+///
+/// A no-op if `src` has no extended attributes.
+#[cfg(unix)]
+fn append_xattrs_pax_header<W: Write>(
+    builder: &mut tar::Builder<Encoder<'static, BufWriter<W>>>,
+    src: &Utf8Path,
+    mtime: u64,
+) -> Result<(), ArchiveCreateError> {
+    let mut pax_data = Vec::new();
+    for name in xattr::list(src.as_std_path()).map_err(ArchiveCreateError::OutputArchiveIo)? {
+        let Some(value) =
+            xattr::get(src.as_std_path(), &name).map_err(ArchiveCreateError::OutputArchiveIo)?
+        else {
+            continue;
+        };
+        let key = format!("SCHILY.xattr.{}", name.to_string_lossy());
+        let value = String::from_utf8_lossy(&value);
+        pax_data.extend_from_slice(pax_record(&key, &value).as_bytes());
+    }
+    if pax_data.is_empty() {
+        return Ok(());
+    }
+
+    let mut header = tar::Header::new_ustar();
+    header.set_entry_type(tar::EntryType::XHeader);
+    header.set_size(pax_data.len() as u64);
+    header.set_mtime(mtime);
+    header.set_cksum();
+    builder
+        .append(&header, io::Cursor::new(pax_data))
+        .map_err(ArchiveCreateError::OutputArchiveIo)
+}
+
+/// Builds a single PAX extended header record: `"<len> <key>=<value>\n"`, where `<len>` is the
+/// record's own length in bytes. The length is self-referential (it includes its own digit
+/// count), so it's computed via the usual fixed-point iteration.
+#[cfg(unix)]
+fn pax_record(key: &str, value: &str) -> String {
+    let mut len = key.len() + value.len() + 3;
+    loop {
+        let record = format!("{len} {key}={value}\n");
+        if record.len() == len {
+            return record;
+        }
+        len = record.len();
+    }
 }
 
 #[cfg(test)]
@@ -324,7 +903,545 @@ mod tests {
             ArchiveFormat::autodetect("foo/bar.tar.zst".as_ref()).unwrap(),
             ArchiveFormat::TarZst,
         );
+        assert_eq!(
+            ArchiveFormat::autodetect("foo.zip".as_ref()).unwrap(),
+            ArchiveFormat::Zip,
+        );
+        assert_eq!(
+            ArchiveFormat::autodetect("foo/bar.zip".as_ref()).unwrap(),
+            ArchiveFormat::Zip,
+        );
         ArchiveFormat::autodetect("foo".as_ref()).unwrap_err();
         ArchiveFormat::autodetect("/".as_ref()).unwrap_err();
     }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_normalize_unix_mode() {
+        assert_eq!(normalize_unix_mode(0o100644), 0o644);
+        assert_eq!(normalize_unix_mode(0o100600), 0o644);
+        assert_eq!(normalize_unix_mode(0o100755), 0o755);
+        assert_eq!(normalize_unix_mode(0o104755), 0o755); // setuid bit is discarded
+        assert_eq!(normalize_unix_mode(0o100664), 0o644);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_pax_record() {
+        // The length prefix must account for its own digit count. The first guess leaves those
+        // digits out, so even a short record takes a second round of the fixed-point loop...
+        assert_eq!(pax_record("a", "b"), "6 a=b\n");
+        // ...and so does a three-digit one (first guess 116, settled length 119).
+        let value = "x".repeat(92);
+        let record = pax_record("SCHILY.xattr.user.foo", &value);
+        assert!(record.starts_with("119 "));
+        assert_eq!(record.len(), 119);
+    }
+
+    #[test]
+    fn test_extra_path_rule_set() {
+        // With no rules, the default action always applies.
+        let rules = ExtraPathRuleSet::new(ExtraPathAction::Exclude);
+        assert_eq!(
+            rules.action_for("foo.txt".as_ref()),
+            ExtraPathAction::Exclude
+        );
+
+        // A later rule overrides an earlier one for paths that match both.
+        let rules = ExtraPathRuleSet::new(ExtraPathAction::Exclude)
+            .add_rule(ExtraPathRule::include("**/*.txt").unwrap())
+            .add_rule(ExtraPathRule::exclude("**/secret.txt").unwrap());
+        assert_eq!(
+            rules.action_for("notes.txt".as_ref()),
+            ExtraPathAction::Include
+        );
+        assert_eq!(
+            rules.action_for("dir/secret.txt".as_ref()),
+            ExtraPathAction::Exclude
+        );
+        assert_eq!(
+            rules.action_for("image.png".as_ref()),
+            ExtraPathAction::Exclude
+        );
+    }
+
+    #[test]
+    fn test_select_extra_dir_paths_does_not_prune_included_descendants() {
+        // Regression test: a default-exclude rule set with a narrow include glob must still walk
+        // into subdirectories, since a directory's own rel path never matches a glob like
+        // `**/*.txt` even though files under it do.
+        let unique = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let root = std::env::temp_dir().join(format!("nextest-archiver-test-{unique}"));
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::write(root.join("sub").join("keep.txt"), b"keep").unwrap();
+        std::fs::write(root.join("sub").join("skip.bin"), b"skip").unwrap();
+
+        let root = Utf8PathBuf::from_path_buf(root).unwrap();
+        let rules = ExtraPathRuleSet::new(ExtraPathAction::Exclude)
+            .add_rule(ExtraPathRule::include("**/*.txt").unwrap());
+
+        let selected = select_extra_dir_paths(&root, &rules, None).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            selected,
+            vec![Utf8PathBuf::from("sub/keep.txt")],
+            "sub/ must still be walked even though the directory itself doesn't match **/*.txt",
+        );
+    }
+}
+
+/// An async counterpart to [`Archiver`], for callers whose pipeline needs to overlap archiving
+/// with other concurrent I/O (e.g. uploading the archive to an object store while it's still
+/// being written) without blocking an executor thread.
+///
+/// Only the `.tar.zst` backend is available here. The zip backend needs to seek back and rewrite
+/// its central directory in `finish`, which doesn't have an async equivalent that's worth the
+/// complexity for a format that mainly exists as a Windows fallback; build a zip archive with
+/// [`archive_to_file`]/[`archive_to_writer`] instead.
+///
+/// This mirrors [`xmr-btc-swap`'s adoption of `tokio-tar`](https://github.com/comit-network/xmr-btc-swap),
+/// which shows the same shape: an async tar builder wrapping an async zstd encoder, with the
+/// same `archive`/`append_path`/`append_dir_all`/`append_from_memory` methods as the sync
+/// version, just `async`.
+///
+/// `extra_paths` and `reproducible` have the same meaning here as in [`archive_to_writer`].
+/// `preserve_symlinks` is not supported on this path: symlink and xattr preservation go through
+/// `append_xattrs_pax_header`, which is written against the sync `tar::Builder` type, and
+/// porting it to `tokio_tar::Builder` is out of scope here. Callers that need
+/// `preserve_symlinks: true` should use [`archive_to_file`]/[`archive_to_writer`] instead;
+/// passing `true` here is rejected up front rather than silently dropped.
+#[cfg(feature = "async-archive")]
+pub mod async_archive {
+    use super::*;
+    use async_compression::tokio::write::ZstdEncoder;
+    use tokio::io::AsyncWrite;
+    use tokio_tar::Builder as AsyncTarBuilder;
+
+    /// Archives test binaries along with metadata to `writer`, asynchronously.
+    ///
+    /// See [`archive_to_writer`] for the meaning of `output_file` and the other parameters; this
+    /// is the async equivalent for use from a `tokio` runtime.
+    ///
+    /// `preserve_symlinks: true` is rejected with [`ArchiveCreateError::OutputArchiveIo`]: see the
+    /// module-level docs for why this path doesn't support it.
+    pub async fn archive_to_async_writer<'a, F, W>(
+        binary_list: &'a BinaryList,
+        cargo_metadata: &'a str,
+        path_mapper: &'a PathMapper,
+        extra_paths: &'a [ExtraPathMapping],
+        reproducible: Option<u64>,
+        preserve_symlinks: bool,
+        zstd_level: i32,
+        output_file: &'a Utf8Path,
+        writer: W,
+        mut callback: F,
+    ) -> Result<usize, ArchiveCreateError>
+    where
+        F: FnMut(ArchiveEvent<'a>) -> io::Result<()>,
+        W: AsyncWrite + Unpin,
+    {
+        if preserve_symlinks {
+            return Err(ArchiveCreateError::OutputArchiveIo(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "preserve_symlinks is not supported by archive_to_async_writer; \
+                 use archive_to_file/archive_to_writer instead",
+            )));
+        }
+
+        let test_binary_count = binary_list.rust_binaries.len();
+        let non_test_binary_count = binary_list.rust_build_meta.non_test_binaries.len();
+        let linked_path_count = binary_list.rust_build_meta.linked_paths.len();
+
+        callback(ArchiveEvent::ArchiveStarted {
+            test_binary_count,
+            non_test_binary_count,
+            linked_path_count,
+            output_file,
+        })
+        .map_err(ArchiveCreateError::ReporterIo)?;
+
+        let archiver = AsyncArchiver::new(
+            binary_list,
+            cargo_metadata,
+            path_mapper,
+            extra_paths,
+            reproducible,
+            zstd_level,
+            writer,
+        )?;
+        let (_, file_count, extra_file_count) = archiver.archive().await?;
+
+        if extra_file_count > 0 {
+            callback(ArchiveEvent::ExtraFilesArchived { extra_file_count })
+                .map_err(ArchiveCreateError::ReporterIo)?;
+        }
+
+        Ok(file_count)
+    }
+
+    struct AsyncArchiver<'a, W: AsyncWrite + Unpin> {
+        binary_list: &'a BinaryList,
+        cargo_metadata: &'a str,
+        path_mapper: &'a PathMapper,
+        extra_paths: &'a [ExtraPathMapping],
+        builder: AsyncTarBuilder<ZstdEncoder<W>>,
+        unix_timestamp: u64,
+        reproducible: Option<u64>,
+        file_count: usize,
+    }
+
+    impl<'a, W: AsyncWrite + Unpin> AsyncArchiver<'a, W> {
+        fn new(
+            binary_list: &'a BinaryList,
+            cargo_metadata: &'a str,
+            path_mapper: &'a PathMapper,
+            extra_paths: &'a [ExtraPathMapping],
+            reproducible: Option<u64>,
+            zstd_level: i32,
+            writer: W,
+        ) -> Result<Self, ArchiveCreateError> {
+            let encoder = ZstdEncoder::with_quality(
+                writer,
+                async_compression::Level::Precise(zstd_level),
+            );
+            let unix_timestamp = match reproducible {
+                Some(epoch) => epoch,
+                None => SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .expect("current time should be after 1970-01-01")
+                    .as_secs(),
+            };
+
+            Ok(Self {
+                binary_list,
+                cargo_metadata,
+                path_mapper,
+                extra_paths,
+                builder: AsyncTarBuilder::new(encoder),
+                unix_timestamp,
+                reproducible,
+                file_count: 0,
+            })
+        }
+
+        async fn archive(mut self) -> Result<(W, usize, usize), ArchiveCreateError> {
+            let binaries_metadata = self
+                .binary_list
+                .to_string(OutputFormat::Serializable(SerializableFormat::JsonPretty))
+                .map_err(ArchiveCreateError::CreateBinaryList)?;
+
+            self.append_from_memory(BINARIES_METADATA_FILE_NAME, &binaries_metadata)
+                .await?;
+            self.append_from_memory(CARGO_METADATA_FILE_NAME, self.cargo_metadata)
+                .await?;
+
+            let target_dir = &self.binary_list.rust_build_meta.target_directory;
+
+            let mut rust_binaries: Vec<_> = self.binary_list.rust_binaries.iter().collect();
+            if self.reproducible.is_some() {
+                rust_binaries.sort_by(|a, b| a.path.cmp(&b.path));
+            }
+            for binary in rust_binaries {
+                let rel_path = binary
+                    .path
+                    .strip_prefix(target_dir.parent().expect("target dir cannot be the root"))
+                    .expect("binary paths must be within target directory");
+                let rel_path = convert_rel_path_to_forward_slash(rel_path);
+                self.append_path(&binary.path, &rel_path).await?;
+            }
+
+            let mut non_test_binaries: Vec<_> = self
+                .binary_list
+                .rust_build_meta
+                .non_test_binaries
+                .iter()
+                .flat_map(|(_, binaries)| binaries)
+                .collect();
+            if self.reproducible.is_some() {
+                non_test_binaries.sort_by(|a, b| a.path.cmp(&b.path));
+            }
+            for non_test_binary in non_test_binaries {
+                let src_path = self
+                    .binary_list
+                    .rust_build_meta
+                    .target_directory
+                    .join(&non_test_binary.path);
+                let src_path = self.path_mapper.map_binary(src_path);
+
+                let rel_path = Utf8Path::new("target").join(&non_test_binary.path);
+                let rel_path = convert_rel_path_to_forward_slash(&rel_path);
+                self.append_path(&src_path, &rel_path).await?;
+            }
+
+            for linked_path in &self.binary_list.rust_build_meta.linked_paths {
+                let src_path = self
+                    .binary_list
+                    .rust_build_meta
+                    .target_directory
+                    .join(linked_path);
+                let src_path = self.path_mapper.map_binary(src_path);
+
+                let rel_path = Utf8Path::new("target").join(linked_path);
+                let rel_path = convert_rel_path_to_forward_slash(&rel_path);
+                self.append_dir_all(&rel_path, &src_path).await?;
+            }
+
+            // Add any user-specified extra files, filtered by their match rules.
+            let mut extra_file_count = 0;
+            for extra_path in self.extra_paths {
+                extra_file_count += self.append_extra_path(extra_path).await?;
+            }
+
+            let encoder = self
+                .builder
+                .into_inner()
+                .await
+                .map_err(ArchiveCreateError::OutputArchiveIo)?;
+            let writer = encoder
+                .into_inner()
+                .map_err(ArchiveCreateError::OutputArchiveIo)?;
+
+            Ok((writer, self.file_count, extra_file_count))
+        }
+
+        async fn append_from_memory(
+            &mut self,
+            name: &str,
+            contents: &str,
+        ) -> Result<(), ArchiveCreateError> {
+            let mut header = tokio_tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mtime(self.unix_timestamp);
+            header.set_mode(0o664);
+            header.set_cksum();
+
+            self.builder
+                .append_data(&mut header, name, contents.as_bytes())
+                .await
+                .map_err(ArchiveCreateError::OutputArchiveIo)?;
+            self.file_count += 1;
+            Ok(())
+        }
+
+        // Mirrors the sync `Archiver::append_dir_all`, but reads directory entries and file
+        // contents through `tokio::fs` so a slow filesystem (e.g. a network mount) doesn't block
+        // the executor thread.
+        async fn append_dir_all(
+            &mut self,
+            rel_path: &Utf8Path,
+            src_path: &Utf8Path,
+        ) -> Result<(), ArchiveCreateError> {
+            let mut stack = vec![src_path.to_path_buf()];
+
+            while let Some(src) = stack.pop() {
+                let mut read_dir = tokio::fs::read_dir(&src).await.map_err(|error| {
+                    ArchiveCreateError::InputFileRead {
+                        path: src.clone(),
+                        is_dir: Some(true),
+                        error,
+                    }
+                })?;
+
+                let mut children = Vec::new();
+                while let Some(entry) =
+                    read_dir
+                        .next_entry()
+                        .await
+                        .map_err(|error| ArchiveCreateError::InputFileRead {
+                            path: src.clone(),
+                            is_dir: Some(true),
+                            error,
+                        })?
+                {
+                    let entry_path = Utf8PathBuf::try_from(entry.path())
+                        .expect("directory entries under a UTF-8 root are UTF-8");
+                    let file_type =
+                        entry
+                            .file_type()
+                            .await
+                            .map_err(|error| ArchiveCreateError::InputFileRead {
+                                path: entry_path.clone(),
+                                is_dir: None,
+                                error,
+                            })?;
+                    // Like the sync walk with `follow: true`, descend into symlinks that point
+                    // at directories.
+                    let is_dir = file_type.is_dir()
+                        || (file_type.is_symlink()
+                            && tokio::fs::metadata(&entry_path)
+                                .await
+                                .map_or(false, |metadata| metadata.is_dir()));
+
+                    children.push((entry_path, is_dir));
+                }
+                // Directory iteration order is filesystem-dependent; sort it so reproducible
+                // archives don't depend on it. Push in reverse so the stack (a LIFO) still pops
+                // entries in ascending order.
+                if self.reproducible.is_some() {
+                    children.sort_by(|a, b| a.0.cmp(&b.0));
+                }
+                for (entry_path, is_dir) in children.into_iter().rev() {
+                    if is_dir {
+                        stack.push(entry_path);
+                        continue;
+                    }
+
+                    let dest = rel_path.join(entry_path.strip_prefix(src_path).unwrap());
+                    self.append_path(&entry_path, &dest).await?;
+                }
+            }
+
+            Ok(())
+        }
+
+        // Mirrors the sync `Archiver::append_extra_path`, including pruning directories the rule
+        // set excludes outright; see that method for the rationale.
+        async fn append_extra_path(
+            &mut self,
+            extra_path: &ExtraPathMapping,
+        ) -> Result<usize, ArchiveCreateError> {
+            let root_name = extra_path
+                .root
+                .file_name()
+                .unwrap_or(EXTRA_FILES_PREFIX);
+            let dest_root = Utf8Path::new(EXTRA_FILES_PREFIX).join(root_name);
+            let mut extra_file_count = 0;
+
+            if !extra_path.root.is_dir() {
+                let rel_path = Utf8Path::new(root_name);
+                if extra_path.rules.action_for(rel_path) == ExtraPathAction::Include {
+                    let dest = convert_rel_path_to_forward_slash(&dest_root);
+                    self.append_path(&extra_path.root, &dest).await?;
+                    extra_file_count += 1;
+                }
+                return Ok(extra_file_count);
+            }
+
+            let mut stack = vec![extra_path.root.clone()];
+            while let Some(src) = stack.pop() {
+                let mut read_dir = tokio::fs::read_dir(&src).await.map_err(|error| {
+                    ArchiveCreateError::InputFileRead {
+                        path: src.clone(),
+                        is_dir: Some(true),
+                        error,
+                    }
+                })?;
+
+                let mut children = Vec::new();
+                while let Some(entry) =
+                    read_dir
+                        .next_entry()
+                        .await
+                        .map_err(|error| ArchiveCreateError::InputFileRead {
+                            path: src.clone(),
+                            is_dir: Some(true),
+                            error,
+                        })?
+                {
+                    let entry_path = Utf8PathBuf::try_from(entry.path())
+                        .expect("directory entries under a UTF-8 root are UTF-8");
+                    let file_type =
+                        entry
+                            .file_type()
+                            .await
+                            .map_err(|error| ArchiveCreateError::InputFileRead {
+                                path: entry_path.clone(),
+                                is_dir: None,
+                                error,
+                            })?;
+
+                    children.push((entry_path, file_type.is_dir()));
+                }
+                // Directory iteration order is filesystem-dependent; sort it so reproducible
+                // archives don't depend on it. Push in reverse so the stack (a LIFO) still pops
+                // entries in ascending order.
+                if self.reproducible.is_some() {
+                    children.sort_by(|a, b| a.0.cmp(&b.0));
+                }
+
+                for (entry_path, is_dir) in children.into_iter().rev() {
+                    let rel_path = entry_path
+                        .strip_prefix(&extra_path.root)
+                        .expect("extra file paths must be within their root");
+
+                    if is_dir {
+                        // Prune a directory only if a rule explicitly excludes it. Falling back
+                        // to `default_action` isn't enough to prune: a glob like `**/*.txt` never
+                        // matches the directory path itself, so relying on the resolved action
+                        // would drop files further down the tree that an include rule was meant
+                        // to match.
+                        let prune = matches!(
+                            extra_path.rules.matching_rule(rel_path),
+                            Some(rule) if rule.action == ExtraPathAction::Exclude
+                        );
+                        if !prune {
+                            stack.push(entry_path);
+                        }
+                        continue;
+                    }
+
+                    if extra_path.rules.action_for(rel_path) == ExtraPathAction::Include {
+                        let dest = convert_rel_path_to_forward_slash(&dest_root.join(rel_path));
+                        self.append_path(&entry_path, &dest).await?;
+                        extra_file_count += 1;
+                    }
+                }
+            }
+
+            Ok(extra_file_count)
+        }
+
+        async fn append_path(
+            &mut self,
+            src: &Utf8Path,
+            dest: &Utf8Path,
+        ) -> Result<(), ArchiveCreateError> {
+            if let Some(mtime) = self.reproducible {
+                // Build the header ourselves, as the sync backend does for the same reason: so
+                // the entry is stamped with a deterministic mtime/mode rather than whatever the
+                // file's real metadata says.
+                let mut file =
+                    tokio::fs::File::open(src.as_std_path())
+                        .await
+                        .map_err(|error| ArchiveCreateError::InputFileRead {
+                            path: src.to_owned(),
+                            is_dir: Some(false),
+                            error,
+                        })?;
+                let metadata =
+                    file.metadata()
+                        .await
+                        .map_err(|error| ArchiveCreateError::InputFileRead {
+                            path: src.to_owned(),
+                            is_dir: Some(false),
+                            error,
+                        })?;
+
+                let mut header = tokio_tar::Header::new_gnu();
+                header.set_metadata_in_mode(&metadata, tokio_tar::HeaderMode::Deterministic);
+                header.set_mtime(mtime);
+                header.set_cksum();
+
+                self.builder
+                    .append_data(&mut header, dest, &mut file)
+                    .await
+                    .map_err(ArchiveCreateError::OutputArchiveIo)?;
+            } else {
+                self.builder
+                    .append_path_with_name(src, dest)
+                    .await
+                    .map_err(|error| ArchiveCreateError::InputFileRead {
+                        path: src.to_owned(),
+                        is_dir: Some(false),
+                        error,
+                    })?;
+            }
+            self.file_count += 1;
+            Ok(())
+        }
+    }
 }
\ No newline at end of file
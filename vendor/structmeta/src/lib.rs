@@ -142,6 +142,9 @@ pub use arg_types::*;
 ///
 /// Causes a compile error and outputs the code generated by `#[derive(ToTokens)]` as an error message.
 // #[include_doc("../../doc/to_tokens.md", end)]
+// TODO(chunk1-6): a to_tokens(with = ...) per-field override and angle-bracket grouping need a
+// change to structmeta_derive, which isn't vendored in this tree -- no macro source to extend.
+// TODO(chunk2-5): same blocker for a companion #[derive(ToTokens)] round-trip emission path.
 pub use structmeta_derive::ToTokens;
 
 // #[include_doc("../../doc/parse.md", start)]
@@ -431,6 +434,9 @@ pub use structmeta_derive::ToTokens;
 ///
 /// Causes a compile error and outputs the code generated by `#[derive(Parse)]` as an error message.
 // #[include_doc("../../doc/parse.md", end)]
+// TODO(chunk1-4): a custom field parser hook (parse(with = "path")) needs a change to
+// structmeta_derive, which isn't vendored in this tree -- no macro source to extend.
+// TODO(chunk1-5): same blocker for combined lookahead error messages on enum Parse.
 pub use structmeta_derive::Parse;
 
 // #[include_doc("../../doc/struct_meta.md", start)]
@@ -1012,4 +1018,14 @@ pub use structmeta_derive::Parse;
 /// assert_eq!(result.x, false);
 /// ```
 // #[include_doc("../../doc/struct_meta.md", end)]
+// TODO(chunk1-1): rename_all case conversion needs a change to structmeta_derive, which isn't
+// vendored in this tree (no source directory, only this facade crate is present) -- nothing to
+// build the feature on top of yet.
+// TODO(chunk1-2): same blocker for alias/aliases on named parameters.
+// TODO(chunk1-3): same blocker for default-value expressions on named parameters.
+// TODO(chunk2-1): same blocker for a bare #[struct_meta(default)] flag.
+// TODO(chunk2-2): same blocker for #[derive(StructMeta)] on enums.
+// TODO(chunk2-3): same blocker for generalizing name_filter into a heck-backed rename_all.
+// TODO(chunk2-4): same blocker for repeated aliases and deprecation warnings on named fields.
+// TODO(chunk2-6): same blocker for post-parse #[struct_meta(validate = "fn")] hooks.
 pub use structmeta_derive::StructMeta;